@@ -2,13 +2,86 @@ use byteorder::{NativeEndian, ReadBytesExt};
 use fftw::array::AlignedVec;
 use fftw::plan::*;
 use fftw::types::*;
+use fftw::wisdom;
 use num_complex::Complex;
+use rayon::prelude::*;
 use ron::de::from_reader;
 use serde::Deserialize;
 use std::f64::consts::PI;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
+use thiserror::Error;
+
+#[cfg(feature = "python")]
+mod python;
+
+/// Errors produced by the `crosscorr` library, preserving the underlying
+/// cause (the offending path, I/O error, or RON parse error) for diagnostics.
+#[derive(Debug, Error)]
+pub enum CrosscorrError {
+    /// Failed to open, create, read from, or write to `path`.
+    #[error("I/O error for {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The RON configuration file could not be parsed.
+    #[error("failed to parse RON configuration: {0}")]
+    RonParse(#[from] ron::de::Error),
+    /// FFTW was unable to create a transform plan.
+    #[error("unable to create FFTW plan")]
+    FftwPlan,
+    /// The grid file at `path` ended before the expected number of cells had
+    /// been read; `cell` is the 0-based index of the cell that was short.
+    #[error("short read from {path}: expected a value for cell {cell} but the file ended")]
+    ShortRead { path: String, cell: usize },
+    /// `load_grid` was asked for a grid index other than 1 or 2.
+    #[error("invalid grid index {0}: expected 1 or 2")]
+    BadGridIndex(usize),
+    /// No configuration file path was given on the command line.
+    #[error("incorrect command-line argument: expected a configuration file path")]
+    MissingArg,
+    /// A `Binning` scheme was too small to form at least one bin: `Linear`
+    /// or `Log` with `nbins == 0`, or `Edges` with fewer than 2 values.
+    #[error("invalid binning configuration: {0}")]
+    InvalidBinning(String),
+    /// `Config::los` was the zero vector, which has no direction to
+    /// normalize into a line-of-sight unit vector.
+    #[error("line-of-sight vector must be non-zero")]
+    DegenerateLos,
+}
+
+/// Either a single scalar, broadcast to all three axes, or an explicit
+/// per-axis `[x, y, z]` triple. Lets `Config::ngrid`/`Config::boxsize` stay
+/// backward compatible with RON files that specify a single cubic value.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ScalarOrArray3<T> {
+    Scalar(T),
+    Array([T; 3]),
+}
+
+fn deserialize_ngrid<'de, D>(deserializer: D) -> Result<[u32; 3], D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match ScalarOrArray3::<u32>::deserialize(deserializer)? {
+        ScalarOrArray3::Scalar(n) => Ok([n; 3]),
+        ScalarOrArray3::Array(n) => Ok(n),
+    }
+}
+
+fn deserialize_boxsize<'de, D>(deserializer: D) -> Result<[f32; 3], D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match ScalarOrArray3::<f32>::deserialize(deserializer)? {
+        ScalarOrArray3::Scalar(b) => Ok([b; 3]),
+        ScalarOrArray3::Array(b) => Ok(b),
+    }
+}
 
 /// A struct containing the configuration information to run the program, read
 /// at runtime from a RON file.
@@ -20,8 +93,14 @@ use std::io::BufReader;
 ///     grid1_filename: String::from("/path/to/grid1"),
 ///     grid2_filename: String::from("/path/to/grid2"),
 ///     output_filename: String::from("/path/to/output"),
-///     ngrid: 2048,
-///     boxsize: 160.0,
+///     ngrid: [2048, 2048, 2048],
+///     boxsize: [160.0, 160.0, 160.0],
+///     window: Some(MassAssignment::Cic),
+///     correct_both: true,
+///     binning: None,
+///     los: Some([0.0, 0.0, 1.0]),
+///     plan: PlanConfig::Measure,
+///     wisdom_file: Some(String::from("/path/to/wisdom")),
 /// }
 /// ```
 #[derive(Debug, Deserialize)]
@@ -29,8 +108,204 @@ pub struct Config {
     pub grid1_filename: String,
     pub grid2_filename: String,
     pub output_filename: String,
-    pub ngrid: u32,
-    pub boxsize: f32,
+    /// Number of cells along each axis. A scalar in the RON file is
+    /// broadcast to all three axes for a cubic grid.
+    #[serde(deserialize_with = "deserialize_ngrid")]
+    pub ngrid: [u32; 3],
+    /// Box size (in cMpc/h) along each axis. A scalar in the RON file is
+    /// broadcast to all three axes for a cubic box.
+    #[serde(deserialize_with = "deserialize_boxsize")]
+    pub boxsize: [f32; 3],
+    /// Mass-assignment scheme to deconvolve from the input grids, if any.
+    #[serde(default)]
+    pub window: Option<MassAssignment>,
+    /// Whether `window` should be deconvolved from both fields rather than
+    /// just one (e.g. when both grids were produced with the same assignment
+    /// scheme).
+    #[serde(default)]
+    pub correct_both: bool,
+    /// Spherical k-space binning scheme for the output power spectrum. When
+    /// omitted, defaults to the classic unit-width bins of the fundamental
+    /// mode, taken as the mean of the per-axis `kf_d = 2*pi/boxsize_d`.
+    #[serde(default)]
+    pub binning: Option<Binning>,
+    /// Line-of-sight axis used to compute the redshift-space power spectrum
+    /// multipoles `P_0`, `P_2`, `P_4` (need not be normalized). When `None`,
+    /// only the isotropic monopole `pow_spec` is produced.
+    #[serde(default)]
+    pub los: Option<[f64; 3]>,
+    /// Rigor used when building the FFTW plan in `perform_fft`. Defaults to
+    /// `Estimate`, which plans instantly but transforms slower; `Measure` or
+    /// `Patient` pay a one-off planning cost for a faster transform, best
+    /// amortized by setting `wisdom_file`.
+    #[serde(default)]
+    pub plan: PlanConfig,
+    /// Path to an FFTW wisdom file. If present, wisdom accumulated from
+    /// previous `Measure`/`Patient` plans for this grid shape is imported
+    /// before planning, and the updated wisdom is exported back afterwards.
+    #[serde(default)]
+    pub wisdom_file: Option<String>,
+}
+
+/// Multipole orders reported in `Output::pow_spec_multipoles` when `los` is
+/// set, in order.
+const MULTIPOLE_ELLS: [i32; 3] = [0, 2, 4];
+
+/// Evaluates the Legendre polynomial of order `ell` (one of 0, 2, 4) at `mu`.
+fn legendre(ell: i32, mu: f64) -> f64 {
+    match ell {
+        0 => 1.0,
+        2 => 0.5 * (3.0 * mu * mu - 1.0),
+        4 => (35.0 * mu.powi(4) - 30.0 * mu * mu + 3.0) / 8.0,
+        _ => unreachable!("only multipoles 0, 2, 4 are supported"),
+    }
+}
+
+/// Specification of the k-space bin edges used to spherically average the
+/// power spectrum.
+#[derive(Debug, Deserialize, Clone)]
+pub enum Binning {
+    /// `nbins` equal-width bins spanning `0` to the largest `|k|` on the
+    /// grid (the Nyquist frequency along the box diagonal).
+    Linear { nbins: usize },
+    /// `nbins` logarithmically-spaced bins spanning `[kmin, kmax]`.
+    Log { nbins: usize, kmin: f64, kmax: f64 },
+    /// Explicit bin edges; need not be evenly spaced.
+    Edges(Vec<f64>),
+}
+
+impl Binning {
+    /// Builds the sorted array of bin edges (length `nbins + 1`) for this
+    /// scheme. `kmax` is the largest `|k|` reachable on the grid (the
+    /// Nyquist frequency along the box diagonal), used by `Linear`.
+    ///
+    /// Fails with [`CrosscorrError::InvalidBinning`] if the scheme can't
+    /// produce at least one bin (`nbins == 0`, or fewer than 2 `Edges`), or
+    /// if `Log`'s `kmin`/`kmax` don't satisfy `0 < kmin < kmax`.
+    fn edges(&self, kmax: f64) -> Result<Vec<f64>, CrosscorrError> {
+        match self {
+            Binning::Linear { nbins } => {
+                if *nbins < 1 {
+                    return Err(CrosscorrError::InvalidBinning(
+                        "Linear binning requires nbins >= 1".to_string(),
+                    ));
+                }
+                Ok((0..=*nbins)
+                    .map(|i| kmax * (i as f64) / (*nbins as f64))
+                    .collect())
+            }
+            Binning::Log { nbins, kmin, kmax } => {
+                if *nbins < 1 {
+                    return Err(CrosscorrError::InvalidBinning(
+                        "Log binning requires nbins >= 1".to_string(),
+                    ));
+                }
+                if !kmin.is_finite() || *kmin <= 0.0 || *kmax <= *kmin {
+                    return Err(CrosscorrError::InvalidBinning(
+                        "Log binning requires 0 < kmin < kmax".to_string(),
+                    ));
+                }
+                let lograt = (kmax / kmin).ln();
+                Ok((0..=*nbins)
+                    .map(|i| kmin * (lograt * (i as f64) / (*nbins as f64)).exp())
+                    .collect())
+            }
+            Binning::Edges(edges) => {
+                if edges.len() < 2 {
+                    return Err(CrosscorrError::InvalidBinning(
+                        "Edges binning requires at least 2 edges".to_string(),
+                    ));
+                }
+                let mut edges = edges.clone();
+                edges.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                Ok(edges)
+            }
+        }
+    }
+}
+
+/// Classic binning used when no `Binning` is given in the config: unit-width
+/// bins of the fundamental mode, i.e. `|k|` rounded to the nearest `kf`.
+fn classic_edges(kf: f64, kmax: f64) -> Vec<f64> {
+    let n = (kmax / kf).ceil() as usize + 1;
+    (0..=n).map(|i| kf * (i as f64 - 0.5)).collect()
+}
+
+/// Finds the index of the bin containing `k`, given sorted `edges`, or
+/// `None` if `k` falls outside `[edges[0], edges[last]]`.
+fn find_bin(edges: &[f64], k: f64) -> Option<usize> {
+    if k < edges[0] || k > edges[edges.len() - 1] {
+        return None;
+    }
+    match edges.binary_search_by(|probe| probe.partial_cmp(&k).unwrap()) {
+        Ok(i) => Some(i.min(edges.len() - 2)),
+        Err(i) => Some(i - 1),
+    }
+}
+
+/// Builds the signed wavenumber of each of an axis' `ngrid_d` frequency
+/// indices, for fundamental mode `kf_d`: `[0, kf_d, 2*kf_d, ..., -2*kf_d,
+/// -kf_d]`, in FFT frequency order.
+fn axis_freqs(ngrid_d: usize, kf_d: f64) -> Vec<f64> {
+    let nhalf = ngrid_d / 2;
+    let mut w = Vec::with_capacity(ngrid_d);
+    for i in 0..=nhalf {
+        w.push(kf_d * (i as f64));
+    }
+    for i in (nhalf + 1)..ngrid_d {
+        w.push(kf_d * ((i as isize - ngrid_d as isize) as f64));
+    }
+    w
+}
+
+/// Mass-assignment scheme used to grid the input fields, whose window
+/// function is deconvolved from the power spectrum.
+///
+/// The transfer function along each axis is `sinc(pi*k/(2*kny))` raised to
+/// a power `p` that depends on the scheme: Nearest-Grid-Point (`p = 1`),
+/// Cloud-in-Cell (`p = 2`), or Triangular-Shaped-Cloud (`p = 3`).
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub enum MassAssignment {
+    Ngp,
+    Cic,
+    Tsc,
+}
+
+impl MassAssignment {
+    fn power(self) -> i32 {
+        match self {
+            MassAssignment::Ngp => 1,
+            MassAssignment::Cic => 2,
+            MassAssignment::Tsc => 3,
+        }
+    }
+}
+
+/// Rigor used to build the FFTW plan in [`perform_fft`]: cheaper settings
+/// skip straight to a transform algorithm, while pricier ones measure (or
+/// exhaustively time) several candidate algorithms first in exchange for a
+/// faster transform. The planning cost is only worth paying when the
+/// resulting plan (or its exported wisdom, via `Config::wisdom_file`) will
+/// be reused across many transforms of the same grid shape.
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+pub enum PlanConfig {
+    /// Build a plan instantly from heuristics, without timing anything.
+    #[default]
+    Estimate,
+    /// Time a handful of candidate algorithms before picking the fastest.
+    Measure,
+    /// Time many more candidate algorithms; slower to plan, faster to run.
+    Patient,
+}
+
+impl PlanConfig {
+    fn flag(self) -> Flag {
+        match self {
+            PlanConfig::Estimate => Flag::Estimate,
+            PlanConfig::Measure => Flag::Measure,
+            PlanConfig::Patient => Flag::Patient,
+        }
+    }
 }
 
 impl Config {
@@ -41,34 +316,51 @@ impl Config {
     /// ```
     /// let config = Config::new(env::args()).unwrap();
     /// ```
-    pub fn new(mut args: std::env::Args) -> Result<Config, &'static str> {
+    pub fn new(mut args: std::env::Args) -> Result<Config, CrosscorrError> {
         args.next();
 
         // Match command-line argument for configuration filename
         let config_filename = match args.next() {
             Some(arg) => arg,
-            None => return Err("Incorrect command-line argument."),
+            None => return Err(CrosscorrError::MissingArg),
         };
 
         // Open configuration file
         println!("\nReading configuration file: {}", config_filename);
-        let f = match File::open(&config_filename) {
-            Ok(file) => file,
-            Err(_) => return Err("Unable to open configuration file."),
-        };
+        let f = File::open(&config_filename).map_err(|source| CrosscorrError::Io {
+            path: config_filename.clone(),
+            source,
+        })?;
 
         // Decode RON format of configuration file
-        let config: Config = match from_reader(f) {
-            Ok(x) => x,
-            Err(_) => return Err("Unable to read configuration from file."),
-        };
+        let config: Config = from_reader(f)?;
 
         // Print configuration
         println!("\ngrid1 path:  {}", config.grid1_filename);
         println!("grid1 path:  {}", config.grid2_filename);
         println!("output path: {}", config.output_filename);
-        println!("ngrid:       {} cells on a side", config.ngrid);
-        println!("boxsize:     {} cMpc/h", config.boxsize);
+        println!("ngrid:       {:?} cells", config.ngrid);
+        println!("boxsize:     {:?} cMpc/h", config.boxsize);
+        match config.window {
+            Some(window) => println!(
+                "window:      deconvolving {:?} mass assignment from {}",
+                window,
+                if config.correct_both { "both fields" } else { "one field" }
+            ),
+            None => println!("window:      no mass-assignment correction"),
+        }
+        match config.los {
+            Some(los) => println!(
+                "los:         computing P0, P2, P4 multipoles along {:?}",
+                los
+            ),
+            None => println!("los:         computing isotropic monopole only"),
+        }
+        println!("plan:        {:?}", config.plan);
+        match &config.wisdom_file {
+            Some(path) => println!("wisdom:      importing/exporting from {}", path),
+            None => println!("wisdom:      not persisted"),
+        }
 
         Ok(config)
     }
@@ -81,6 +373,10 @@ pub struct Output {
     pub pow_spec: Vec<f64>,
     pub deltasqk: Vec<f64>,
     pub iweights: Vec<i64>,
+    /// Redshift-space power spectrum multipoles `P_0, P_2, P_4`, one `Vec`
+    /// per order in [`MULTIPOLE_ELLS`], each of length `w.len()`. Empty when
+    /// `Config::los` was not set.
+    pub pow_spec_multipoles: Vec<Vec<f64>>,
 }
 
 impl Output {
@@ -91,85 +387,196 @@ impl Output {
     /// ```
     /// output.save_result(&config).unwrap();
     /// ```
-    pub fn save_result(&self, config: &Config) -> Result<(), &'static str> {
-        println!("\nSaving results to: {}", &config.output_filename);
+    pub fn save_result(&self, config: &Config) -> Result<(), CrosscorrError> {
+        self.save_result_to(&config.output_filename)
+    }
 
-        // Open output file
-        let mut f = match File::create(&config.output_filename) {
-            Ok(file) => file,
-            Err(_) => return Err("Unable to open output file!"),
+    /// Serializes the power spectrum to `output_filename`. Split out from
+    /// [`Output::save_result`] so [`OutputWriter`] can write a completed
+    /// `Output` without needing a whole `Config`.
+    fn save_result_to(&self, output_filename: &str) -> Result<(), CrosscorrError> {
+        println!("\nSaving results to: {}", output_filename);
+
+        let to_err = |source: std::io::Error| CrosscorrError::Io {
+            path: output_filename.to_string(),
+            source,
         };
-        match writeln!(f, "# w pow_spec deltasqk iweights") {
-            Ok(_) => (),
-            Err(err) => {
-                eprintln!("{}", err);
-                return Err("Unable to save output!")
-            },
+
+        // Open output file
+        let mut f = File::create(output_filename).map_err(to_err)?;
+        let mut header = String::from("# w pow_spec deltasqk iweights");
+        for ell in MULTIPOLE_ELLS.iter().take(self.pow_spec_multipoles.len()) {
+            header.push_str(&format!(" p{}", ell));
         }
+        writeln!(f, "{}", header).map_err(to_err)?;
 
-        let nhalf: usize = (config.ngrid / 2) as usize;
-        for n in 0..nhalf {
-            match writeln!(
-                f,
+        for n in 0..self.w.len() {
+            let mut line = format!(
                 "{} {} {} {}",
                 self.w[n], self.pow_spec[n], self.deltasqk[n], self.iweights[n]
-            ) {
-                Ok(_) => (),
-                Err(err) => {
-                    eprintln!("{}", err);
-                    return Err("Unable to save output!")
-                },
+            );
+            for multipole in &self.pow_spec_multipoles {
+                line.push_str(&format!(" {}", multipole[n]));
             }
+            writeln!(f, "{}", line).map_err(to_err)?;
         }
 
         Ok(())
     }
 }
 
+/// Depth of the channel between the main thread and [`OutputWriter`]'s
+/// background thread. Kept small so a writer that falls behind applies
+/// backpressure to the computation rather than letting finished `Output`s
+/// pile up in memory.
+const OUTPUT_CHANNEL_DEPTH: usize = 3;
+
+/// Serializes completed `Output`s to disk on a dedicated background thread,
+/// so that computing the next grid pair in a batch can overlap with writing
+/// out the previous one.
+///
+/// # Examples
+///
+/// ```
+/// let writer = OutputWriter::new();
+/// writer.send(output, config.output_filename.clone());
+/// for err in writer.finish() {
+///     eprintln!("Error: {}", err);
+/// }
+/// ```
+pub struct OutputWriter {
+    sender: Option<std::sync::mpsc::SyncSender<(Output, String)>>,
+    handle: Option<std::thread::JoinHandle<Vec<CrosscorrError>>>,
+}
+
+impl OutputWriter {
+    /// Spawns the background writer thread.
+    pub fn new() -> OutputWriter {
+        let (sender, receiver) =
+            std::sync::mpsc::sync_channel::<(Output, String)>(OUTPUT_CHANNEL_DEPTH);
+        let handle = std::thread::spawn(move || {
+            let mut errors = Vec::new();
+            for (output, output_filename) in receiver {
+                if let Err(err) = output.save_result_to(&output_filename) {
+                    errors.push(err);
+                }
+            }
+            errors
+        });
+        OutputWriter {
+            sender: Some(sender),
+            handle: Some(handle),
+        }
+    }
+
+    /// Queues `output` to be written to `output_filename`, blocking while
+    /// `OUTPUT_CHANNEL_DEPTH` earlier outputs are already queued behind the
+    /// one being written.
+    pub fn send(&self, output: Output, output_filename: String) {
+        self.sender
+            .as_ref()
+            .expect("output writer already shut down")
+            .send((output, output_filename))
+            .expect("output writer thread panicked");
+    }
+
+    /// Closes the channel, waits for all pending writes to finish, and
+    /// returns every error encountered while writing (in the order the
+    /// writes were queued). Prefer this over letting `OutputWriter` simply
+    /// drop whenever a caller needs to act on write failures rather than
+    /// just log them.
+    pub fn finish(mut self) -> Vec<CrosscorrError> {
+        self.sender.take();
+        match self.handle.take() {
+            Some(handle) => handle.join().expect("output writer thread panicked"),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Default for OutputWriter {
+    fn default() -> OutputWriter {
+        OutputWriter::new()
+    }
+}
+
+impl Drop for OutputWriter {
+    fn drop(&mut self) {
+        // Drop the sender first so the writer thread's receiving `for` loop
+        // sees the channel close and exits, then join it to wait for any
+        // outstanding writes to finish. Callers who need to know about write
+        // failures rather than just have them logged should call `finish`
+        // explicitly instead of relying on drop.
+        self.sender.take();
+        if let Some(handle) = self.handle.take() {
+            if let Ok(errors) = handle.join() {
+                for err in errors {
+                    eprintln!("Error: {}", err);
+                }
+            }
+        }
+    }
+}
+
 /// Loads a grid stored at `filename` (in a custom binary format) into an
 /// `fftw::array::AlignedVec` object. This custom format stores the 3D grid as
 /// a 1D array of values. The data should be stored as deviations from the mean,
 /// i.e. delta = (x - mean(x)) / mean(x).
 ///
+/// The grid is real-valued, so it is loaded into a real `AlignedVec<f64>`
+/// buffer ready for the R2C transform in [`perform_fft`], rather than a
+/// complex buffer with a wasted zero imaginary part.
+///
 /// # Examples
 ///
 /// ```
 /// let grid1 = load_grid(&config, 1).unwrap();
 /// ```
-pub fn load_grid(config: &Config, num: usize) -> Result<AlignedVec<c64>, &'static str> {
+pub fn load_grid(config: &Config, num: usize) -> Result<AlignedVec<f64>, CrosscorrError> {
     let filename = match num {
         1 => &config.grid1_filename,
         2 => &config.grid2_filename,
-        _ => return Err("Need to load either grid 1 or 2!"),
+        _ => return Err(CrosscorrError::BadGridIndex(num)),
     };
     println!("\nOpening grid from file: {}", filename);
-    let ngrid: usize = config.ngrid as usize;
+    let [nx, ny, nz] = config.ngrid.map(|n| n as usize);
 
     // Allocate AlignedVec array to hold grid
-    let ngrid3 = ngrid * ngrid * ngrid;
+    let ngrid3 = nx * ny * nz;
     let mut grid = AlignedVec::new(ngrid3);
 
     // Open binary file
-    let f = match File::open(filename) {
-        Ok(file) => file,
-        Err(_) => return Err("Unable to open grid file!"),
-    };
+    let f = File::open(filename).map_err(|source| CrosscorrError::Io {
+        path: filename.clone(),
+        source,
+    })?;
     let mut buf_reader = BufReader::new(f);
 
     // Read in array from binary file
-    for elem in grid.iter_mut() {
-        let cell = match buf_reader.read_f32::<NativeEndian>() {
-            Ok(val) => val,
-            Err(_) => return Err("Problem reading values from file!"),
-        };
-        *elem = c64::new(f64::from(cell), 0.0);
+    for (cell, elem) in grid.iter_mut().enumerate() {
+        let val = buf_reader
+            .read_f32::<NativeEndian>()
+            .map_err(|source| {
+                if source.kind() == std::io::ErrorKind::UnexpectedEof {
+                    CrosscorrError::ShortRead {
+                        path: filename.clone(),
+                        cell,
+                    }
+                } else {
+                    CrosscorrError::Io {
+                        path: filename.clone(),
+                        source,
+                    }
+                }
+            })?;
+        *elem = f64::from(val);
     }
     println!("Successfully read {} cells!", ngrid3);
     println!("Sanity print:");
     grid[0..5].iter()
         .enumerate()
         .for_each(|(i, elem)| {
-        println!("grid1[{}] = {:.3e} + {:.3e}i", i, elem.re, elem.im);
+        println!("grid1[{}] = {:.3e}", i, elem);
     });
 
     Ok(grid)
@@ -177,6 +584,12 @@ pub fn load_grid(config: &Config, num: usize) -> Result<AlignedVec<c64>, &'stati
 
 /// Performs FFT on grids
 ///
+/// Uses the real-to-complex `R2CPlan64` rather than a full complex-to-complex
+/// transform, since the input grids are real-valued: this roughly halves
+/// both the FFTW buffer allocation and the transform cost for large `ngrid`.
+/// The output only contains the non-redundant half of the spectrum, with
+/// shape `nx * ny * (nz/2 + 1)` for `ngrid = [nx, ny, nz]`.
+///
 /// # Examples
 ///
 /// ```
@@ -184,29 +597,43 @@ pub fn load_grid(config: &Config, num: usize) -> Result<AlignedVec<c64>, &'stati
 /// ```
 pub fn perform_fft(
     config: &Config,
-    grid1: AlignedVec<c64>,
-    grid2: AlignedVec<c64>,
-) -> Result<(AlignedVec<c64>, AlignedVec<c64>), &'static str> {
+    grid1: AlignedVec<f64>,
+    grid2: AlignedVec<f64>,
+) -> Result<(AlignedVec<c64>, AlignedVec<c64>), CrosscorrError> {
     println!("\nPerforming FFTs...");
-    let ngrid: usize = config.ngrid as usize;
+    let [nx, ny, nz] = config.ngrid.map(|n| n as usize);
+
+    // Import any wisdom accumulated by a previous run, so a `Measure`/
+    // `Patient` plan for this grid shape doesn't need to be re-timed.
+    if let Some(path) = &config.wisdom_file {
+        if wisdom::import_wisdom_file(path) {
+            println!("Imported FFTW wisdom from {}", path);
+        }
+    }
 
     // Create FFTW plan
-    let shape = [ngrid, ngrid, ngrid];
-    let mut plan: C2CPlan64 = match C2CPlan::aligned(&shape[..], Sign::Forward, Flag::Estimate) {
-        Ok(p) => p,
-        Err(_) => return Err("Unable to create FFTW plan."),
-    };
+    let shape = [nx, ny, nz];
+    let mut plan: R2CPlan64 = R2CPlan::aligned(&shape[..], config.plan.flag())
+        .map_err(|_| CrosscorrError::FftwPlan)?;
     println!("Plan created!");
 
     // Perform FFT on grids
-    let ngrid3 = ngrid * ngrid * ngrid;
+    let ncomplex = nx * ny * (nz / 2 + 1);
 
-    let out1 = fft_from_plan(ngrid3, grid1, &mut plan)?;
+    let out1 = fft_from_plan(ncomplex, grid1, &mut plan)?;
     println!("First grid FFT complete!");
 
-    let out2 = fft_from_plan(ngrid3, grid2, &mut plan)?;
+    let out2 = fft_from_plan(ncomplex, grid2, &mut plan)?;
     println!("Second grid FFT complete!");
 
+    // Export the (possibly newly-learned) wisdom back to disk so the next
+    // run on this machine and grid shape can skip straight to planning.
+    if let Some(path) = &config.wisdom_file {
+        if wisdom::export_wisdom_file(path) {
+            println!("Exported FFTW wisdom to {}", path);
+        }
+    }
+
     // Sanity prints
     println!("FFTs performed... Sanity check:");
     for n in 0..10 {
@@ -216,20 +643,52 @@ pub fn perform_fft(
     Ok((out1, out2))
 }
 
-/// Use FFTW3 plan to perform FFT
+/// Use FFTW3 plan to perform R2C FFT
 fn fft_from_plan(
-    ngrid3: usize,
-    mut grid: AlignedVec<c64>,
-    plan: &mut C2CPlan64,
-) -> Result<AlignedVec<c64>, &'static str> {
-    let mut out = AlignedVec::new(ngrid3);
-    match plan.c2c(&mut grid, &mut out) {
-        Ok(_) => (),
-        Err(_) => return Err("Failed to FFT grid."),
-    };
+    ncomplex: usize,
+    mut grid: AlignedVec<f64>,
+    plan: &mut R2CPlan64,
+) -> Result<AlignedVec<c64>, CrosscorrError> {
+    let mut out = AlignedVec::new(ncomplex);
+    plan.r2c(&mut grid, &mut out)
+        .map_err(|_| CrosscorrError::FftwPlan)?;
     Ok(out)
 }
 
+/// Thread-local accumulator for the per-bin sums in [`correlate`]'s mode
+/// loop, combined across worker threads with [`BinAccumulator::merge`].
+struct BinAccumulator {
+    pow_sum: Vec<f64>,
+    k_sum: Vec<f64>,
+    iweights: Vec<i64>,
+    multipole_sum: Vec<Vec<f64>>,
+}
+
+impl BinAccumulator {
+    fn new(nbins: usize, nmultipoles: usize) -> BinAccumulator {
+        BinAccumulator {
+            pow_sum: vec![0.0; nbins],
+            k_sum: vec![0.0; nbins],
+            iweights: vec![0; nbins],
+            multipole_sum: vec![vec![0.0; nbins]; nmultipoles],
+        }
+    }
+
+    fn merge(mut self, other: BinAccumulator) -> BinAccumulator {
+        for bin in 0..self.pow_sum.len() {
+            self.pow_sum[bin] += other.pow_sum[bin];
+            self.k_sum[bin] += other.k_sum[bin];
+            self.iweights[bin] += other.iweights[bin];
+        }
+        for (ell_idx, sums) in other.multipole_sum.into_iter().enumerate() {
+            for (bin, val) in sums.into_iter().enumerate() {
+                self.multipole_sum[ell_idx][bin] += val;
+            }
+        }
+        self
+    }
+}
+
 /// Calculates the cross power spectrum of the given 3D grids (note if the same
 /// grid is given twice then this is the auto power spectrum).
 ///
@@ -242,137 +701,476 @@ pub fn correlate(
     config: &Config,
     out1: AlignedVec<c64>,
     out2: AlignedVec<c64>,
-) -> Result<Output, &'static str> {
+) -> Result<Output, CrosscorrError> {
     println!("\nCalculating power spectrum...");
 
-    if cfg!(feature = "ngp_correction_single") {
-        println!("Correcting for NGP mass assignment of one field!");
-    } else if cfg!(feature = "cic_correction_single") {
-        println!("Correcting for CIC mass assignment of one field!");
-    } else if cfg!(feature = "ngp_correction_both") {
-        println!("Correcting for NGP mass assignment of both fields!");
-    } else if cfg!(feature = "cic_correction_both") {
-        println!("Correcting for CIC mass assignment of both fields!");
-    }
-
-    let ngrid: usize = config.ngrid as usize;
-    let boxsize: f64 = f64::from(config.boxsize);
-
-    // Calculate power spectrum
-    let kf: f64 = 2.0 * PI / boxsize;
-    let coeff: f64 = (boxsize / (2.0 * PI)).powf(2.0);
-    let nhalf: usize = ngrid / 2;
-
-    #[cfg(any(
-        feature = "ngp_correction_single",
-        feature = "ngp_correction_both",
-        feature = "cic_correction_single",
-        feature = "cic_correction_both"
-    ))]
-    let kny: f64 = PI * config.ngrid as f64 / boxsize;
-
-    let mut w: Vec<f64> = Vec::with_capacity(ngrid);
-    for i in 0..=nhalf {
-        w.push(kf * (i as f64));
-    }
-    for i in (nhalf + 1)..ngrid {
-        w.push(kf * ((i as isize - ngrid as isize) as f64));
-    }
-
-    let mut pow_spec: Vec<f64> = vec![0.0; ngrid];
-    let mut iweights: Vec<i64> = vec![0; ngrid];
-
-    for i in 0..ngrid {
-        let iper = if i >= nhalf { ngrid - i } else { i };
-        for j in 0..ngrid {
-            let jper = if j >= nhalf { ngrid - j } else { j };
-            for k in 0..ngrid {
-                let kper = if k >= nhalf { ngrid - k } else { k };
-                let r: f64 = (iper * iper + jper * jper + kper * kper) as f64;
-                let m: usize = (0.5 + r.sqrt()) as usize;
-                iweights[m] += 1;
-
-                let g = w[i] * w[i] + w[j] * w[j] + w[k] * w[k];
-                if g != 0.0 {
-                    let scale: usize = (0.5 + (g * coeff).sqrt()) as usize;
-                    let index: usize = k + ngrid * (j + ngrid * i);
-                    let mut contrib: Complex<f64> =
-                        out1[index] * out2[index].conj() + out1[index].conj() * out2[index];
-
-                    #[cfg(feature = "ngp_correction_single")]
-                    {
-                        // Correct for Nearest-Grid-Point mass assignment
-                        let wngp = sinc(PI * w[i] as f64 / (2.0 * kny))
-                            * sinc(PI * w[j] as f64 / (2.0 * kny))
-                            * sinc(PI * w[k] as f64 / (2.0 * kny));
-                        contrib.re /= wngp;
-                    }
+    if let Some(window) = config.window {
+        println!(
+            "Correcting for {:?} mass assignment of {}!",
+            window,
+            if config.correct_both { "both fields" } else { "one field" }
+        );
+    }
 
-                    #[cfg(feature = "cic_correction_single")]
-                    {
-                        // Correct for Cloud-in-Cell mass assignment
-                        let wcic = (sinc(PI * w[i] as f64 / (2.0 * kny))
-                            * sinc(PI * w[j] as f64 / (2.0 * kny))
-                            * sinc(PI * w[k] as f64 / (2.0 * kny)))
-                        .powi(2);
-                        contrib.re /= wcic;
-                    }
+    let [nx, ny, nz]: [usize; 3] = config.ngrid.map(|n| n as usize);
+    let [bx, by, bz]: [f64; 3] = config.boxsize.map(f64::from);
 
-                    #[cfg(feature = "ngp_correction_both")]
-                    {
-                        // Correct for Nearest-Grid-Point mass assignment
-                        let wngp = sinc(PI * w[i] as f64 / (2.0 * kny))
-                            * sinc(PI * w[j] as f64 / (2.0 * kny))
-                            * sinc(PI * w[k] as f64 / (2.0 * kny));
-                        contrib.re /= wngp * wngp;
-                    }
+    // Calculate power spectrum, per axis since the grid and box need not be
+    // cubic: each axis has its own fundamental mode and Nyquist frequency.
+    let kf: [f64; 3] = [2.0 * PI / bx, 2.0 * PI / by, 2.0 * PI / bz];
+    let nhalf: [usize; 3] = [nx / 2, ny / 2, nz / 2];
+    let kny: [f64; 3] = [
+        PI * nx as f64 / bx,
+        PI * ny as f64 / by,
+        PI * nz as f64 / bz,
+    ];
 
-                    #[cfg(feature = "cic_correction_both")]
-                    {
-                        // Correct for Cloud-in-Cell mass assignment
-                        let wcic = (sinc(PI * w[i] as f64 / (2.0 * kny))
-                            * sinc(PI * w[j] as f64 / (2.0 * kny))
-                            * sinc(PI * w[k] as f64 / (2.0 * kny)))
-                        .powi(2);
-                        contrib.re /= wcic * wcic;
-                    }
+    let wx = axis_freqs(nx, kf[0]);
+    let wy = axis_freqs(ny, kf[1]);
+    let wz = axis_freqs(nz, kf[2]);
 
-                    pow_spec[scale] += contrib.re / 2.0;
-                }
+    // Build the sorted k-space bin edges for the spherical average, and
+    // find each mode's bin by binary search rather than rounding to the
+    // nearest integer multiple of kf.
+    let kmax_diag = (kny[0] * kny[0] + kny[1] * kny[1] + kny[2] * kny[2]).sqrt();
+    let edges = match &config.binning {
+        Some(binning) => binning.edges(kmax_diag)?,
+        None => classic_edges((kf[0] + kf[1] + kf[2]) / 3.0, kmax_diag),
+    };
+    let nbins = edges.len() - 1;
+
+    // Unit line-of-sight vector for the redshift-space multipoles, if requested.
+    let nhat: Option<[f64; 3]> = match config.los {
+        Some(los) => {
+            let norm = (los[0] * los[0] + los[1] * los[1] + los[2] * los[2]).sqrt();
+            if norm == 0.0 {
+                return Err(CrosscorrError::DegenerateLos);
             }
+            Some([los[0] / norm, los[1] / norm, los[2] / norm])
         }
-    }
+        None => None,
+    };
+    let nmultipoles = if nhat.is_some() { MULTIPOLE_ELLS.len() } else { 0 };
+
+    // The R2C transform only stores the non-redundant half of the spectrum
+    // along the last axis (k in 0..=nz/2). Interior planes of that half
+    // each stand in for a missing conjugate-mirror mode at k -> nz-k, so
+    // they are double-weighted; k=0 is its own mirror and keeps unit
+    // weight. The plane at k=nz/2 is only self-mirrored when nz is even
+    // (the true Nyquist mode); for odd nz the highest stored plane still
+    // has a distinct mirror outside the stored half and must be
+    // double-weighted like any other interior plane. i and j still range
+    // over the full grid, so no such folding is needed on those axes.
+    //
+    // The outer `i` index is partitioned across threads with rayon, each
+    // accumulating into its own `BinAccumulator`, which are then reduced
+    // into the final per-bin sums (a clean map-reduce since every update
+    // below is a per-bin `+=`).
+    let nlast = nhalf[2] + 1;
+    let BinAccumulator {
+        pow_sum,
+        k_sum,
+        iweights,
+        mut multipole_sum,
+    } = (0..nx)
+        .into_par_iter()
+        .fold(
+            || BinAccumulator::new(nbins, nmultipoles),
+            |mut acc, i| {
+                for j in 0..ny {
+                    for k in 0..nlast {
+                        let weight: i64 =
+                            if k == 0 || (nz % 2 == 0 && k == nhalf[2]) { 1 } else { 2 };
+
+                        let g = wx[i] * wx[i] + wy[j] * wy[j] + wz[k] * wz[k];
+                        if g != 0.0 {
+                            let kmag = g.sqrt();
+                            let bin = match find_bin(&edges, kmag) {
+                                Some(bin) => bin,
+                                None => continue,
+                            };
+                            let index: usize = k + nlast * (j + ny * i);
+                            let mut contrib: Complex<f64> = out1[index] * out2[index].conj()
+                                + out1[index].conj() * out2[index];
+
+                            if let Some(window) = config.window {
+                                // Deconvolve the mass-assignment window function
+                                let wind = (sinc(PI * wx[i] / (2.0 * kny[0]))
+                                    * sinc(PI * wy[j] / (2.0 * kny[1]))
+                                    * sinc(PI * wz[k] / (2.0 * kny[2])))
+                                .powi(window.power());
+                                if config.correct_both {
+                                    contrib.re /= wind * wind;
+                                } else {
+                                    contrib.re /= wind;
+                                }
+                            }
+
+                            acc.iweights[bin] += weight;
+                            acc.k_sum[bin] += weight as f64 * kmag;
+                            let mode_power = weight as f64 * contrib.re / 2.0;
+                            acc.pow_sum[bin] += mode_power;
+
+                            if let Some(nhat) = nhat {
+                                let mu = (wx[i] * nhat[0] + wy[j] * nhat[1] + wz[k] * nhat[2])
+                                    / kmag;
+                                for (ell_idx, &ell) in MULTIPOLE_ELLS.iter().enumerate() {
+                                    acc.multipole_sum[ell_idx][bin] +=
+                                        mode_power * legendre(ell, mu);
+                                }
+                            }
+                        }
+                    }
+                }
+                acc
+            },
+        )
+        .reduce(|| BinAccumulator::new(nbins, nmultipoles), BinAccumulator::merge);
     println!("Power spectrum calculated. Normalising...");
 
-    // Normalise power spectrum
+    // Normalise power spectrum, reporting the mode-count-weighted mean k of
+    // each bin (rather than its nominal centre) alongside pow_spec.
     let pisq: f64 = 2.0 * PI * PI;
-    let mut deltasqk: Vec<f64> = Vec::with_capacity(nhalf);
+    let mut w_out: Vec<f64> = Vec::with_capacity(nbins);
+    let mut pow_spec: Vec<f64> = Vec::with_capacity(nbins);
+    let mut deltasqk: Vec<f64> = Vec::with_capacity(nbins);
+    let volume = bx * by * bz;
+    let ngrid_total = (nx * ny * nz) as f64;
+    let norm_factor = volume / (ngrid_total * ngrid_total);
+
+    for i in 0..nbins {
+        if iweights[i] > 0 {
+            let kmean = k_sum[i] / iweights[i] as f64;
+            let p = pow_sum[i] * norm_factor / iweights[i] as f64;
+            w_out.push(kmean);
+            deltasqk.push(kmean.powf(3.0) * p / pisq);
+            pow_spec.push(p);
+        } else {
+            w_out.push(0.0);
+            deltasqk.push(0.0);
+            pow_spec.push(0.0);
+        }
+    }
 
-    for i in 0..nhalf {
-        pow_spec[i] *= boxsize.powi(3) / (ngrid as f64).powi(6);
-        pow_spec[i] /= iweights[i] as f64;
-        deltasqk.push(w[i].powf(3.0) * pow_spec[i] / pisq);
+    // Normalise the multipoles: P_l(k) = (2l+1)/N_modes * sum of weighted,
+    // Legendre-multiplied contributions per bin.
+    let mut pow_spec_multipoles: Vec<Vec<f64>> = Vec::with_capacity(multipole_sum.len());
+    for (ell_idx, sums) in multipole_sum.drain(..).enumerate() {
+        let ell = MULTIPOLE_ELLS[ell_idx] as f64;
+        let mut p_ell = Vec::with_capacity(nbins);
+        for i in 0..nbins {
+            if iweights[i] > 0 {
+                p_ell.push((2.0 * ell + 1.0) * sums[i] * norm_factor / iweights[i] as f64);
+            } else {
+                p_ell.push(0.0);
+            }
+        }
+        pow_spec_multipoles.push(p_ell);
     }
 
     // Return final output
     Ok(Output {
-        w,
+        w: w_out,
         pow_spec,
         deltasqk,
         iweights,
+        pow_spec_multipoles,
     })
 }
 
-#[cfg(any(
-    feature = "ngp_correction_single",
-    feature = "ngp_correction_both",
-    feature = "cic_correction_single",
-    feature = "cic_correction_both"
-))]
 fn sinc(theta: f64) -> f64 {
-    if theta < 1e-20 {
+    if theta.abs() < 1e-20 {
         1.0
     } else {
         (theta.sin() / theta)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The highest plane stored by the R2C half-spectrum (`k = nz/2` rounded
+    // down) is only its own conjugate mirror when `nz` is even. For odd `nz`
+    // it still has a distinct mirror outside the stored half and must be
+    // double-weighted like any other interior plane. Check this by comparing
+    // the monopole computed from the R2C half-spectrum (via `correlate`)
+    // against one computed directly from a full C2C transform of the same
+    // grid, on an odd-sized grid where the two previously disagreed.
+    #[test]
+    fn r2c_monopole_matches_c2c_on_odd_grid() {
+        let n = 5;
+        let ncells = n * n * n;
+
+        let sample = |idx: usize, phase: f64| (idx as f64 * phase).sin();
+
+        let config = Config {
+            grid1_filename: String::new(),
+            grid2_filename: String::new(),
+            output_filename: String::new(),
+            ngrid: [n as u32; 3],
+            boxsize: [1.0; 3],
+            window: None,
+            correct_both: false,
+            binning: Some(Binning::Edges(vec![0.0, 1e6])),
+            los: None,
+            plan: PlanConfig::Estimate,
+            wisdom_file: None,
+        };
+
+        // R2C half-spectrum, as used by `correlate`.
+        let mut real1 = AlignedVec::<f64>::new(ncells);
+        let mut real2 = AlignedVec::<f64>::new(ncells);
+        for idx in 0..ncells {
+            real1[idx] = sample(idx, 0.37);
+            real2[idx] = sample(idx, 0.61);
+        }
+        let mut r2c_plan: R2CPlan64 = R2CPlan::aligned(&[n, n, n], Flag::Estimate).unwrap();
+        let ncomplex = n * n * (n / 2 + 1);
+        let mut half1 = AlignedVec::<c64>::new(ncomplex);
+        let mut half2 = AlignedVec::<c64>::new(ncomplex);
+        r2c_plan.r2c(&mut real1, &mut half1).unwrap();
+        r2c_plan.r2c(&mut real2, &mut half2).unwrap();
+
+        let output = correlate(&config, half1, half2).unwrap();
+
+        // Full C2C transform of the same grid, folded by hand with unit
+        // weight on every one of its `ncells` modes (nothing is missing, so
+        // no Hermitian folding is needed).
+        let mut complex1 = AlignedVec::<c64>::new(ncells);
+        let mut complex2 = AlignedVec::<c64>::new(ncells);
+        for idx in 0..ncells {
+            complex1[idx] = c64::new(sample(idx, 0.37), 0.0);
+            complex2[idx] = c64::new(sample(idx, 0.61), 0.0);
+        }
+        let mut c2c_plan: C2CPlan64 =
+            C2CPlan::aligned(&[n, n, n], Sign::Forward, Flag::Estimate).unwrap();
+        let mut full1 = AlignedVec::<c64>::new(ncells);
+        let mut full2 = AlignedVec::<c64>::new(ncells);
+        c2c_plan.c2c(&mut complex1, &mut full1).unwrap();
+        c2c_plan.c2c(&mut complex2, &mut full2).unwrap();
+
+        let kf = 2.0 * PI;
+        let w = axis_freqs(n, kf);
+        let mut full_sum = 0.0;
+        let mut full_count: i64 = 0;
+        for i in 0..n {
+            for j in 0..n {
+                for k in 0..n {
+                    let g = w[i] * w[i] + w[j] * w[j] + w[k] * w[k];
+                    if g == 0.0 {
+                        continue;
+                    }
+                    let idx = k + n * (j + n * i);
+                    let contrib =
+                        full1[idx] * full2[idx].conj() + full1[idx].conj() * full2[idx];
+                    full_sum += contrib.re / 2.0;
+                    full_count += 1;
+                }
+            }
+        }
+        let norm_factor = 1.0 / (ncells as f64 * ncells as f64);
+        let p_full = full_sum * norm_factor / full_count as f64;
+
+        assert_eq!(output.iweights[0], full_count);
+        assert!(
+            (output.pow_spec[0] - p_full).abs() < 1e-9,
+            "R2C monopole {} != full C2C monopole {}",
+            output.pow_spec[0],
+            p_full
+        );
+    }
+
+    #[test]
+    fn binning_rejects_schemes_with_no_bins() {
+        assert!(matches!(
+            Binning::Linear { nbins: 0 }.edges(1.0),
+            Err(CrosscorrError::InvalidBinning(_))
+        ));
+        assert!(matches!(
+            Binning::Log { nbins: 0, kmin: 0.1, kmax: 1.0 }.edges(1.0),
+            Err(CrosscorrError::InvalidBinning(_))
+        ));
+        assert!(matches!(
+            Binning::Edges(vec![]).edges(1.0),
+            Err(CrosscorrError::InvalidBinning(_))
+        ));
+        assert!(matches!(
+            Binning::Edges(vec![5.0]).edges(1.0),
+            Err(CrosscorrError::InvalidBinning(_))
+        ));
+    }
+
+    #[test]
+    fn binning_rejects_nonpositive_log_kmin() {
+        assert!(matches!(
+            Binning::Log { nbins: 5, kmin: 0.0, kmax: 10.0 }.edges(1.0),
+            Err(CrosscorrError::InvalidBinning(_))
+        ));
+        assert!(matches!(
+            Binning::Log { nbins: 5, kmin: -1.0, kmax: 10.0 }.edges(1.0),
+            Err(CrosscorrError::InvalidBinning(_))
+        ));
+        assert!(matches!(
+            Binning::Log { nbins: 5, kmin: 10.0, kmax: 1.0 }.edges(1.0),
+            Err(CrosscorrError::InvalidBinning(_))
+        ));
+    }
+
+    #[test]
+    fn sinc_is_symmetric_about_zero() {
+        assert_eq!(sinc(0.0), 1.0);
+        for theta in [0.3, 1.5, PI, 10.0] {
+            assert_eq!(sinc(theta), sinc(-theta), "sinc({theta}) != sinc({})", -theta);
+            assert_ne!(sinc(theta), 1.0, "sinc({theta}) should not be the zero-guard value");
+        }
+    }
+
+    #[test]
+    fn window_correction_handles_negative_frequency_modes() {
+        // n=3 gives axis_freqs a negative-frequency entry (-kf at index 2),
+        // so the window deconvolution below is exercised with theta < 0.
+        let n = 3;
+        let ncells = n * n * n;
+        let boxsize = 1.0;
+        let config = Config {
+            grid1_filename: String::new(),
+            grid2_filename: String::new(),
+            output_filename: String::new(),
+            ngrid: [n as u32; 3],
+            boxsize: [boxsize; 3],
+            window: Some(MassAssignment::Ngp),
+            correct_both: false,
+            binning: Some(Binning::Edges(vec![0.0, 1e6])),
+            los: None,
+            plan: PlanConfig::Estimate,
+            wisdom_file: None,
+        };
+
+        let mut real1 = AlignedVec::<f64>::new(ncells);
+        let mut real2 = AlignedVec::<f64>::new(ncells);
+        for idx in 0..ncells {
+            real1[idx] = (idx as f64 * 0.37).sin();
+            real2[idx] = (idx as f64 * 0.61).cos();
+        }
+        let mut r2c_plan: R2CPlan64 = R2CPlan::aligned(&[n, n, n], Flag::Estimate).unwrap();
+        let ncomplex = n * n * (n / 2 + 1);
+        let mut half1 = AlignedVec::<c64>::new(ncomplex);
+        let mut half2 = AlignedVec::<c64>::new(ncomplex);
+        r2c_plan.r2c(&mut real1, &mut half1).unwrap();
+        r2c_plan.r2c(&mut real2, &mut half2).unwrap();
+
+        let with_window = correlate(&config, half1, half2).unwrap();
+
+        // Recompute the same single monopole bin by hand, deconvolving with
+        // the textbook (even) sinc definition independent of the function
+        // under test, and compare against `correlate`'s window-corrected
+        // output. Under the pre-fix guard (`theta < 1e-20`, not
+        // `theta.abs() < 1e-20`) every negative-wx mode would have been left
+        // uncorrected (wind == 1.0), making the two diverge.
+        let kf = 2.0 * PI / boxsize;
+        let kny = PI * n as f64 / boxsize;
+        let even_sinc = |theta: f64| if theta == 0.0 { 1.0 } else { theta.sin() / theta };
+        let w = axis_freqs(n, kf);
+        let nlast = n / 2 + 1;
+
+        let mut real1 = AlignedVec::<f64>::new(ncells);
+        let mut real2 = AlignedVec::<f64>::new(ncells);
+        for idx in 0..ncells {
+            real1[idx] = (idx as f64 * 0.37).sin();
+            real2[idx] = (idx as f64 * 0.61).cos();
+        }
+        let mut half1 = AlignedVec::<c64>::new(ncomplex);
+        let mut half2 = AlignedVec::<c64>::new(ncomplex);
+        r2c_plan.r2c(&mut real1, &mut half1).unwrap();
+        r2c_plan.r2c(&mut real2, &mut half2).unwrap();
+
+        let mut expected_sum = 0.0;
+        let mut count: i64 = 0;
+        for i in 0..n {
+            for j in 0..n {
+                for k in 0..nlast {
+                    let g = w[i] * w[i] + w[j] * w[j] + w[k] * w[k];
+                    if g == 0.0 {
+                        continue;
+                    }
+                    let weight = if k == 0 || (n % 2 == 0 && k == n / 2) { 1.0 } else { 2.0 };
+                    let index = k + nlast * (j + n * i);
+                    let mut contrib =
+                        half1[index] * half2[index].conj() + half1[index].conj() * half2[index];
+                    let wind = even_sinc(PI * w[i] / (2.0 * kny))
+                        * even_sinc(PI * w[j] / (2.0 * kny))
+                        * even_sinc(PI * w[k] / (2.0 * kny));
+                    contrib.re /= wind;
+                    expected_sum += weight * contrib.re / 2.0;
+                    count += weight as i64;
+                }
+            }
+        }
+        let norm_factor = (boxsize * boxsize * boxsize) / (ncells as f64 * ncells as f64);
+        let expected = expected_sum * norm_factor / count as f64;
+
+        assert!(
+            (with_window.pow_spec[0] - expected).abs() < 1e-9,
+            "window-corrected monopole {} != expected {}",
+            with_window.pow_spec[0],
+            expected
+        );
+    }
+
+    #[test]
+    fn output_writer_surfaces_write_failures() {
+        let output = Output {
+            w: vec![1.0],
+            pow_spec: vec![1.0],
+            deltasqk: vec![1.0],
+            iweights: vec![1],
+            pow_spec_multipoles: vec![],
+        };
+
+        let writer = OutputWriter::new();
+        // A path inside a directory that doesn't exist can never be created.
+        writer.send(output, "/nonexistent-crosscorr-test-dir/output.txt".to_string());
+        let errors = writer.finish();
+
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], CrosscorrError::Io { .. }));
+    }
+
+    #[test]
+    fn correlate_rejects_zero_los() {
+        let n = 2;
+        let ncells = n * n * n;
+        let config = Config {
+            grid1_filename: String::new(),
+            grid2_filename: String::new(),
+            output_filename: String::new(),
+            ngrid: [n as u32; 3],
+            boxsize: [1.0; 3],
+            window: None,
+            correct_both: false,
+            binning: None,
+            los: Some([0.0, 0.0, 0.0]),
+            plan: PlanConfig::Estimate,
+            wisdom_file: None,
+        };
+
+        let mut real1 = AlignedVec::<f64>::new(ncells);
+        let mut real2 = AlignedVec::<f64>::new(ncells);
+        for idx in 0..ncells {
+            real1[idx] = (idx as f64 * 0.37).sin();
+            real2[idx] = (idx as f64 * 0.61).cos();
+        }
+        let mut r2c_plan: R2CPlan64 = R2CPlan::aligned(&[n, n, n], Flag::Estimate).unwrap();
+        let ncomplex = n * n * (n / 2 + 1);
+        let mut half1 = AlignedVec::<c64>::new(ncomplex);
+        let mut half2 = AlignedVec::<c64>::new(ncomplex);
+        r2c_plan.r2c(&mut real1, &mut half1).unwrap();
+        r2c_plan.r2c(&mut real2, &mut half2).unwrap();
+
+        assert!(matches!(
+            correlate(&config, half1, half2),
+            Err(CrosscorrError::DegenerateLos)
+        ));
+    }
+}
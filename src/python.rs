@@ -0,0 +1,134 @@
+//! PyO3 bindings exposing the correlation pipeline to Python, so NumPy
+//! workflows can drive the power-spectrum computation directly without the
+//! RON config file / binary grid file round trip. Built as an extension
+//! module with maturin and gated behind the `python` cargo feature, so the
+//! native CLI binaries still build without PyO3.
+
+use crate::{correlate, perform_fft, Config, MassAssignment};
+use fftw::array::AlignedVec;
+use numpy::{IntoPyArray, PyArray1, PyArray3};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+/// Parses the `window` keyword argument into a [`MassAssignment`] scheme.
+fn parse_window(window: Option<&str>) -> PyResult<Option<MassAssignment>> {
+    match window {
+        None => Ok(None),
+        Some("ngp") => Ok(Some(MassAssignment::Ngp)),
+        Some("cic") => Ok(Some(MassAssignment::Cic)),
+        Some("tsc") => Ok(Some(MassAssignment::Tsc)),
+        Some(other) => Err(PyValueError::new_err(format!(
+            "unknown mass-assignment scheme '{}', expected one of: ngp, cic, tsc",
+            other
+        ))),
+    }
+}
+
+/// Returns an error unless `shape` is the cubic `(ngrid, ngrid, ngrid)` shape
+/// that `perform_fft` will plan the FFTW transform against. Checked up front
+/// so a mismatched array can never be executed against a plan sized for a
+/// different grid, which would read/write past the allocated buffer.
+fn check_grid_shape(shape: &[usize], ngrid: u32) -> PyResult<()> {
+    let ngrid = ngrid as usize;
+    if shape == [ngrid, ngrid, ngrid] {
+        Ok(())
+    } else {
+        Err(PyValueError::new_err(format!(
+            "grid shape {:?} does not match ngrid={} (expected ({ngrid}, {ngrid}, {ngrid}))",
+            shape, ngrid
+        )))
+    }
+}
+
+/// Copies a cubic `float32` or `float64` NumPy array of shape `(ngrid, ngrid,
+/// ngrid)` into an `AlignedVec<f64>` FFTW buffer, ready for [`perform_fft`].
+fn grid_to_aligned(grid: &PyAny, ngrid: u32) -> PyResult<AlignedVec<f64>> {
+    if let Ok(arr) = grid.downcast::<PyArray3<f64>>() {
+        let view = unsafe { arr.as_array() };
+        check_grid_shape(view.shape(), ngrid)?;
+        let mut aligned = AlignedVec::new(view.len());
+        for (dst, src) in aligned.iter_mut().zip(view.iter()) {
+            *dst = *src;
+        }
+        Ok(aligned)
+    } else if let Ok(arr) = grid.downcast::<PyArray3<f32>>() {
+        let view = unsafe { arr.as_array() };
+        check_grid_shape(view.shape(), ngrid)?;
+        let mut aligned = AlignedVec::new(view.len());
+        for (dst, src) in aligned.iter_mut().zip(view.iter()) {
+            *dst = f64::from(*src);
+        }
+        Ok(aligned)
+    } else {
+        Err(PyValueError::new_err(
+            "grid must be a float32 or float64 NumPy array of shape (ngrid, ngrid, ngrid)",
+        ))
+    }
+}
+
+/// Computes the cross power spectrum of two real-valued grids held in NumPy
+/// arrays of shape `(ngrid, ngrid, ngrid)`, skipping the RON/binary-file
+/// round trip the CLI binaries require.
+///
+/// Returns `(w, pow_spec, deltasqk, iweights)` as NumPy arrays.
+///
+/// # Examples
+///
+/// ```python
+/// import crosscorr
+/// w, pow_spec, deltasqk, iweights = crosscorr.power_spectrum(
+///     grid1, grid2, ngrid=256, boxsize=100.0,
+/// )
+/// ```
+#[pyfunction]
+#[pyo3(signature = (grid1, grid2, ngrid, boxsize, window=None, correct_both=false))]
+#[allow(clippy::too_many_arguments)]
+fn power_spectrum(
+    py: Python,
+    grid1: &PyAny,
+    grid2: &PyAny,
+    ngrid: u32,
+    boxsize: f32,
+    window: Option<&str>,
+    correct_both: bool,
+) -> PyResult<(
+    Py<PyArray1<f64>>,
+    Py<PyArray1<f64>>,
+    Py<PyArray1<f64>>,
+    Py<PyArray1<i64>>,
+)> {
+    let config = Config {
+        grid1_filename: String::new(),
+        grid2_filename: String::new(),
+        output_filename: String::new(),
+        ngrid: [ngrid; 3],
+        boxsize: [boxsize; 3],
+        window: parse_window(window)?,
+        correct_both,
+        binning: None,
+        los: None,
+        plan: crate::PlanConfig::default(),
+        wisdom_file: None,
+    };
+
+    let aligned1 = grid_to_aligned(grid1, ngrid)?;
+    let aligned2 = grid_to_aligned(grid2, ngrid)?;
+
+    let (out1, out2) = perform_fft(&config, aligned1, aligned2)
+        .map_err(|err| PyValueError::new_err(err.to_string()))?;
+    let output = correlate(&config, out1, out2).map_err(|err| PyValueError::new_err(err.to_string()))?;
+
+    Ok((
+        output.w.into_pyarray(py).into(),
+        output.pow_spec.into_pyarray(py).into(),
+        output.deltasqk.into_pyarray(py).into(),
+        output.iweights.into_pyarray(py).into(),
+    ))
+}
+
+/// The `crosscorr` Python extension module.
+#[pymodule]
+fn crosscorr(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(power_spectrum, m)?)?;
+    Ok(())
+}